@@ -0,0 +1,150 @@
+use crate::crop_image;
+use crate::dither::Dither;
+use crate::resize::ResizeMode;
+use crate::sample::Sample;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::{AnimationDecoder, DynamicImage, Frame, GenericImageView};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Pixelate every frame of an animated GIF independently, preserving each frame's delay, and
+/// re-encode the result as a new animation.
+pub fn process_animated_image(
+    resize_mode: ResizeMode,
+    path: &PathBuf,
+    keep_dimensions: bool,
+    force_crop: bool,
+    centre: bool,
+    overwrite: bool,
+    sample: Sample,
+    dither: Dither,
+) -> Result<(), String> {
+    let source_bytes = std::fs::read(path)
+        .map_err(|_| format!("could not open file at '{}'", path.display()))?;
+
+    let repeat = read_loop_count(&source_bytes);
+
+    let file =
+        File::open(path).map_err(|_| format!("could not open file at '{}'", path.display()))?;
+
+    let decoder = GifDecoder::new(file)
+        .map_err(|_| format!("could not decode image at '{}'", path.display()))?;
+
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(|_| format!("could not decode image at '{}'", path.display()))?;
+
+    let first_frame = frames
+        .first()
+        .ok_or_else(|| format!("'{}' contained no frames", path.display()))?;
+
+    let (width, height) = first_frame.buffer().dimensions();
+    let scale_factor = resize_mode.block_size(width, height);
+
+    if (width % scale_factor != 0 || height % scale_factor != 0)
+        && !force_crop
+        && !resize_mode.auto_crops()
+    {
+        return Err(format!(
+            "image dimensions at '{}' were not divisible by the scale factor. you can force crop the image using the -f flag",
+            path.display()
+        ));
+    }
+
+    let mut new_frames = Vec::with_capacity(frames.len());
+
+    for frame in &frames {
+        let delay = frame.delay();
+
+        let mut image = DynamicImage::ImageRgba8(frame.buffer().clone());
+        image = crop_image(&mut image, scale_factor, centre);
+
+        let (frame_width, frame_height) = (image.width(), image.height());
+        let (new_width, new_height) = (frame_width / scale_factor, frame_height / scale_factor);
+
+        let mut new_buffer = if keep_dimensions {
+            image::RgbaImage::new(frame_width, frame_height)
+        } else {
+            image::RgbaImage::new(new_width, new_height)
+        };
+
+        for x in 0..new_width {
+            for y in 0..new_height {
+                let mut pixels = Vec::with_capacity((scale_factor * scale_factor) as usize);
+
+                for i in 0..scale_factor {
+                    for j in 0..scale_factor {
+                        pixels.push(image.get_pixel(x * scale_factor + i, y * scale_factor + j));
+                    }
+                }
+
+                let pixel = dither.apply(sample.reduce(&pixels), x, y);
+
+                if keep_dimensions {
+                    for i in 0..scale_factor {
+                        for j in 0..scale_factor {
+                            new_buffer.put_pixel(x * scale_factor + i, y * scale_factor + j, pixel);
+                        }
+                    }
+                } else {
+                    new_buffer.put_pixel(x, y, pixel);
+                }
+            }
+        }
+
+        new_frames.push(Frame::from_parts(new_buffer, 0, 0, delay));
+    }
+
+    let directory = &path
+        .ancestors()
+        .nth(1)
+        .unwrap_or_else(|| Path::new("."))
+        .display();
+
+    let original_file_name = &path.file_name().unwrap().to_str().unwrap();
+
+    let file_name = if overwrite {
+        original_file_name.to_string()
+    } else {
+        format!("{}{}", crate::PIXELATED_PREFIX, original_file_name)
+    };
+
+    let mut output = File::create(format!("{}/{}", directory, file_name))
+        .map_err(|_| format!("could not save image at '{}'", path.display()))?;
+
+    let mut encoder = GifEncoder::new(&mut output);
+
+    encoder
+        .set_repeat(repeat)
+        .map_err(|_| format!("could not save image at '{}'", path.display()))?;
+
+    encoder
+        .encode_frames(new_frames)
+        .map_err(|_| format!("could not save image at '{}'", path.display()))
+}
+
+/// Scan the raw GIF bytes for a `NETSCAPE2.0` application extension and read its loop count.
+/// Neither `image`'s `GifDecoder` nor the lower-level `gif` crate it wraps expose this, so the
+/// source's own looping behavior (including "don't loop" for a file with no such extension) has
+/// to be read off the wire format directly instead of defaulting to infinite.
+fn read_loop_count(bytes: &[u8]) -> Repeat {
+    const SIGNATURE: &[u8] = b"NETSCAPE2.0";
+
+    let signature_at = match bytes.windows(SIGNATURE.len()).position(|window| window == SIGNATURE) {
+        Some(position) => position,
+        None => return Repeat::Finite(0),
+    };
+
+    // The sub-block holding the loop count follows the signature as: size byte (always 3),
+    // sub-block id byte (always 1), then the loop count itself as a little-endian u16.
+    let loop_count_at = signature_at + SIGNATURE.len() + 2;
+
+    match bytes.get(loop_count_at..loop_count_at + 2) {
+        Some(&[low, high]) => match u16::from_le_bytes([low, high]) {
+            0 => Repeat::Infinite,
+            count => Repeat::Finite(count),
+        },
+        None => Repeat::Finite(0),
+    }
+}
@@ -0,0 +1,101 @@
+use crate::dither::Dither;
+use crate::format::Format;
+use crate::resize::ResizeMode;
+use crate::sample::Sample;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use twox_hash::XxHash64;
+
+/// A fingerprint of a source file's bytes plus the parameters that affect its pixelated
+/// output. Two runs with the same key would produce byte-identical results.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    pub fn compute(
+        source_bytes: &[u8],
+        resize_mode: ResizeMode,
+        keep_dimensions: bool,
+        force_crop: bool,
+        centre: bool,
+        format: Format,
+        sample: Sample,
+        dither: Dither,
+    ) -> CacheKey {
+        let mut hasher = XxHash64::with_seed(0);
+        let (mode_tag, mode_a, mode_b) = resize_mode.fingerprint();
+        let (format_tag, quality) = format.fingerprint();
+
+        hasher.write(source_bytes);
+        hasher.write_u8(mode_tag);
+        hasher.write_u32(mode_a);
+        hasher.write_u32(mode_b);
+        hasher.write_u8(keep_dimensions as u8);
+        hasher.write_u8(force_crop as u8);
+        hasher.write_u8(centre as u8);
+        hasher.write_u8(format_tag);
+        hasher.write_u8(quality);
+        hasher.write_u8(sample.tag());
+        hasher.write_u8(dither.tag());
+
+        CacheKey(hasher.finish())
+    }
+}
+
+/// A sidecar index, one per target directory, mapping output file names to the `CacheKey`
+/// that produced them. Lets a directory run skip files that are already up to date.
+pub struct CacheIndex {
+    path: PathBuf,
+    entries: HashMap<String, u64>,
+}
+
+impl CacheIndex {
+    /// The sidecar's own file name, so directory walks can skip over it instead of handing it
+    /// to the image decoder as if it were one of the files being processed.
+    pub(crate) const FILE_NAME: &'static str = ".pixelate-cache";
+
+    pub fn load(directory: &Path) -> CacheIndex {
+        let path = directory.join(Self::FILE_NAME);
+
+        let entries = fs::read_to_string(&path)
+            .map(|contents| Self::parse(&contents))
+            .unwrap_or_default();
+
+        CacheIndex { path, entries }
+    }
+
+    fn parse(contents: &str) -> HashMap<String, u64> {
+        contents
+            .lines()
+            .filter_map(|line| {
+                let (output_file_name, key) = line.split_once('\t')?;
+                let key = key.parse::<u64>().ok()?;
+
+                Some((output_file_name.to_string(), key))
+            })
+            .collect()
+    }
+
+    /// Whether `output_path` already holds the result of this exact key, so the work can be
+    /// skipped.
+    pub fn is_fresh(&self, output_file_name: &str, key: CacheKey, output_path: &Path) -> bool {
+        output_path.is_file() && self.entries.get(output_file_name) == Some(&key.0)
+    }
+
+    pub fn record(&mut self, output_file_name: String, key: CacheKey) {
+        self.entries.insert(output_file_name, key.0);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = self
+            .entries
+            .iter()
+            .map(|(output_file_name, key)| format!("{}\t{}", output_file_name, key))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        fs::write(&self.path, contents)
+    }
+}
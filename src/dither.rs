@@ -0,0 +1,54 @@
+use image::Rgba;
+
+/// A 4x4 Bayer threshold matrix, normalized to [0, 1).
+const BAYER_4X4: [[f32; 4]; 4] = [
+    [0.0 / 16.0, 8.0 / 16.0, 2.0 / 16.0, 10.0 / 16.0],
+    [12.0 / 16.0, 4.0 / 16.0, 14.0 / 16.0, 6.0 / 16.0],
+    [3.0 / 16.0, 11.0 / 16.0, 1.0 / 16.0, 9.0 / 16.0],
+    [15.0 / 16.0, 7.0 / 16.0, 13.0 / 16.0, 5.0 / 16.0],
+];
+
+/// The size of each step in the palette that ordered dithering quantizes toward.
+const PALETTE_STEP: f32 = 32.0;
+
+/// How (if at all) the reduced blocks are dithered before being written.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Dither {
+    None,
+    Ordered,
+}
+
+impl Dither {
+    /// A stable tag for this mode, used as part of a cache key.
+    pub fn tag(&self) -> u8 {
+        match self {
+            Dither::None => 0,
+            Dither::Ordered => 1,
+        }
+    }
+
+    /// Apply this dither to a single reduced block, given its position in the output's block
+    /// grid. Alpha is passed through as the sampling strategy reduced it.
+    pub fn apply(&self, pixel: Rgba<u8>, x: u32, y: u32) -> Rgba<u8> {
+        match self {
+            Dither::None => pixel,
+            Dither::Ordered => {
+                let threshold = BAYER_4X4[(x % 4) as usize][(y % 4) as usize];
+                let offset = (threshold - 0.5) * PALETTE_STEP;
+
+                let quantize = |channel: u8| -> u8 {
+                    let stepped = ((channel as f32 + offset) / PALETTE_STEP).round() * PALETTE_STEP;
+
+                    stepped.clamp(0.0, 255.0) as u8
+                };
+
+                Rgba([
+                    quantize(pixel[0]),
+                    quantize(pixel[1]),
+                    quantize(pixel[2]),
+                    pixel[3],
+                ])
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::FormatArg;
+use image::DynamicImage;
+use std::fs;
+use std::path::Path;
+
+/// The concrete output encoding for a processed image, resolved from a `FormatArg` and,
+/// for `auto`, the source file's own format.
+#[derive(Clone, Copy)]
+pub enum Format {
+    Png,
+    Jpeg(u8),
+    WebP,
+}
+
+impl Format {
+    /// Resolve a `FormatArg` into a concrete `Format`. `auto` keeps lossy sources (jpeg/webp)
+    /// lossy and falls back to png for everything else.
+    pub fn resolve(format_arg: FormatArg, quality: u8, source_path: &Path) -> Format {
+        match format_arg {
+            FormatArg::Png => Format::Png,
+            FormatArg::Jpeg => Format::Jpeg(quality),
+            FormatArg::Webp => Format::WebP,
+            FormatArg::Auto => match image::ImageFormat::from_path(source_path) {
+                Ok(image::ImageFormat::Jpeg) => Format::Jpeg(quality),
+                Ok(image::ImageFormat::WebP) => Format::WebP,
+                _ => Format::Png,
+            },
+        }
+    }
+
+    /// A stable tag plus this format's defining number (JPEG quality, otherwise unused), for
+    /// use as part of a cache key.
+    pub fn fingerprint(&self) -> (u8, u8) {
+        match self {
+            Format::Png => (0, 0),
+            Format::Jpeg(quality) => (1, *quality),
+            Format::WebP => (2, 0),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg(_) => "jpg",
+            Format::WebP => "webp",
+        }
+    }
+
+    pub fn save(&self, image: &DynamicImage, path: &Path) -> Result<(), image::ImageError> {
+        match self {
+            Format::Jpeg(quality) => {
+                let mut file = fs::File::create(path)?;
+                image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                    &mut file, *quality,
+                ))
+            }
+            Format::Png => image.save_with_format(path, image::ImageFormat::Png),
+            Format::WebP => image.save_with_format(path, image::ImageFormat::WebP),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, ImageFormat};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// A probe of an image file's format and frame count, used to decide whether it should take
+/// the single-frame or the animated processing path. For a GIF it decodes every frame in order
+/// to count them; other formats are not decoded.
+pub struct Info {
+    pub format: ImageFormat,
+    pub frame_count: usize,
+}
+
+impl Info {
+    /// Sniff the format from the file's own magic bytes (`guess_format`, not the extension),
+    /// then for GIFs count frames with the gif decoder to tell a still image apart from an
+    /// animation.
+    pub fn probe(path: &Path) -> Result<Info, String> {
+        let mut file = File::open(path)
+            .map_err(|_| format!("could not open file at '{}'", path.display()))?;
+
+        let mut header = [0u8; 16];
+        let read = file
+            .read(&mut header)
+            .map_err(|_| format!("could not open file at '{}'", path.display()))?;
+
+        let format = image::guess_format(&header[..read])
+            .map_err(|_| format!("could not determine image format of '{}'", path.display()))?;
+
+        let frame_count = if format == ImageFormat::Gif {
+            let file = File::open(path)
+                .map_err(|_| format!("could not open file at '{}'", path.display()))?;
+            let decoder = GifDecoder::new(file)
+                .map_err(|_| format!("could not decode image at '{}'", path.display()))?;
+            let frames = decoder
+                .into_frames()
+                .collect_frames()
+                .map_err(|_| format!("could not decode image at '{}'", path.display()))?;
+
+            frames.len()
+        } else {
+            1
+        };
+
+        Ok(Info {
+            format,
+            frame_count,
+        })
+    }
+
+    /// Whether this file should take the animated processing path. Only GIFs with more than
+    /// one frame qualify: `process_animated_image` is built on `image`'s `GifDecoder`/
+    /// `GifEncoder`, and this crate's `image` dependency has no APNG or animated WebP encoder
+    /// to round-trip those formats through, so they're decoded as a single still frame.
+    pub fn is_animated_format(&self) -> bool {
+        self.format == ImageFormat::Gif && self.frame_count > 1
+    }
+}
@@ -1,16 +1,59 @@
+mod animation;
+mod cache;
+mod dither;
+mod format;
+mod info;
+mod resize;
+mod sample;
+
+use cache::{CacheIndex, CacheKey};
 use clap::{error::ErrorKind, Command, CommandFactory, Parser};
+use dither::Dither;
+use format::Format;
+use info::Info;
 use image::{io::Reader as ImageReader, DynamicImage, GenericImage, GenericImageView, Rgba};
+use rayon::prelude::*;
+use resize::{parse_fit_dimensions, ResizeMode};
+use sample::Sample;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// The prefix given to output files unless `--overwrite` is passed. Shared so a directory walk
+/// can recognize (and skip re-processing) a prior run's own outputs.
+pub(crate) const PIXELATED_PREFIX: &str = "pixelated_";
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum FormatArg {
+    Auto,
+    Png,
+    Jpeg,
+    Webp,
+}
 
 #[derive(Parser)]
 struct Cli {
     /// The path of the image that you want to process
     path: std::path::PathBuf,
 
-    /// The scale factor by which the image will be scaled down (must be a power of two)
-    scale_factor: u8,
+    /// The scale factor by which the image will be scaled down (at least 2). Omit this and
+    /// use --fit-width, --fit-height, or --fit instead to target an output size directly
+    #[clap(conflicts_with_all = ["fit_width", "fit_height", "fit"])]
+    scale_factor: Option<u32>,
+
+    /// Choose a scale factor so the output is about this many pixels wide
+    #[clap(long, conflicts_with_all = ["fit_height", "fit"])]
+    fit_width: Option<u32>,
+
+    /// Choose a scale factor so the output is about this many pixels tall
+    #[clap(long, conflicts_with_all = ["fit_width", "fit"])]
+    fit_height: Option<u32>,
+
+    /// Choose a scale factor so the output fits within WIDTHxHEIGHT, preserving aspect ratio
+    /// (e.g. `128x128`)
+    #[clap(long, value_parser = parse_fit_dimensions, conflicts_with_all = ["fit_width", "fit_height"])]
+    fit: Option<(u32, u32)>,
 
     /// Keep the dimensions of the output image the same as the input
     #[clap(long, short, action)]
@@ -31,33 +74,87 @@ struct Cli {
     /// Use all optional flags
     #[clap(long, short, action)]
     all: bool,
-}
 
-#[derive(PartialEq)]
-enum ErrorResponse {
-    Ignore,
-    Exit,
+    /// Cap the number of worker threads used when processing a directory (default: all cores)
+    #[clap(long, short)]
+    jobs: Option<usize>,
+
+    /// Output image format. `auto` keeps the source format if it is lossy (jpeg/webp) and
+    /// otherwise falls back to png
+    #[clap(long, value_enum, default_value = "auto")]
+    format: FormatArg,
+
+    /// JPEG quality, only used when the output format is jpeg (1-100)
+    #[clap(long, default_value_t = 85)]
+    quality: u8,
+
+    /// The strategy used to reduce each pixelation block down to a single color. Defaults to
+    /// `mean` with --keep-dimensions and `nearest` without it, matching this tool's
+    /// long-standing output in both cases
+    #[clap(long, value_enum)]
+    sample: Option<Sample>,
+
+    /// Apply ordered (Bayer) dithering to the reduced blocks for a deliberate retro look
+    #[clap(long, value_enum, default_value = "none")]
+    dither: Dither,
 }
 
 fn main() {
     let arguments = Cli::parse();
     let mut command = Cli::command();
 
-    let (path, scale_factor, keep_dimensions, force_crop, centre, overwrite) = (
+    let (path, keep_dimensions, force_crop, centre, overwrite, jobs, format, quality, dither) = (
         arguments.path,
-        arguments.scale_factor,
         arguments.keep_dimensions || arguments.all,
         arguments.force_crop || arguments.all,
         arguments.centre || arguments.all,
         arguments.overwrite || arguments.all,
+        arguments.jobs,
+        arguments.format,
+        arguments.quality,
+        arguments.dither,
     );
 
-    if scale_factor < 2 || scale_factor > 8 {
+    // Baseline behavior averaged each block under --keep-dimensions but took the top-left pixel
+    // otherwise; preserve both when --sample is left unspecified.
+    let sample = arguments.sample.unwrap_or(if keep_dimensions {
+        Sample::Mean
+    } else {
+        Sample::Nearest
+    });
+
+    let resize_mode = match (
+        arguments.scale_factor,
+        arguments.fit_width,
+        arguments.fit_height,
+        arguments.fit,
+    ) {
+        (Some(factor), None, None, None) => ResizeMode::ScaleDown(factor),
+        (None, Some(width), None, None) => ResizeMode::FitWidth(width),
+        (None, None, Some(height), None) => ResizeMode::FitHeight(height),
+        (None, None, None, Some((width, height))) => ResizeMode::Fit(width, height),
+        (None, None, None, None) => {
+            command
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "specify a scale factor, or one of --fit-width, --fit-height, --fit",
+                )
+                .exit();
+        }
+        _ => unreachable!("clap's conflicts_with_all rules out combining these arguments"),
+    };
+
+    if let ResizeMode::ScaleDown(factor) = resize_mode {
+        if factor < 2 {
+            command
+                .error(ErrorKind::InvalidValue, "scale factor must be at least 2")
+                .exit();
+        }
+    }
+
+    if quality < 1 || quality > 100 {
         command
-            .error(
-                ErrorKind::InvalidValue,
-                "scale factor must be between 2 and 8",
-            )
+            .error(ErrorKind::InvalidValue, "quality must be between 1 and 100")
             .exit();
     }
 
@@ -69,21 +166,26 @@ fn main() {
     };
 
     if path_metadata.is_file() {
-        process_image(
-            &mut command,
-            scale_factor,
+        if let Err(message) = process_image(
+            resize_mode,
             &path,
             keep_dimensions,
             force_crop,
             centre,
             overwrite,
-            ErrorResponse::Exit,
-        );
+            format,
+            quality,
+            sample,
+            dither,
+            None,
+        ) {
+            command.error(ErrorKind::Io, message).exit();
+        }
 
         return;
     } else if path_metadata.is_dir() {
-        let paths = match fs::read_dir(&path) {
-            Ok(paths) => paths,
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
             Err(_) => {
                 command
                     .error(ErrorKind::Io, "could not read directory")
@@ -91,9 +193,11 @@ fn main() {
             }
         };
 
-        for path in paths {
-            let path = match path {
-                Ok(path) => path,
+        let mut files = Vec::new();
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
                 Err(_) => {
                     command
                         .error(ErrorKind::Io, "could not read directory")
@@ -101,97 +205,267 @@ fn main() {
                 }
             };
 
-            let path = path.path();
-
-            if path.is_file() {
-                process_image(
-                    &mut command,
-                    scale_factor,
-                    &path,
-                    keep_dimensions,
-                    force_crop,
-                    centre,
-                    overwrite,
-                    ErrorResponse::Ignore,
-                );
+            let entry_path = entry.path();
+
+            let is_own_output = entry_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name == CacheIndex::FILE_NAME || name.starts_with(PIXELATED_PREFIX));
+
+            if entry_path.is_file() && !is_own_output {
+                files.push(entry_path);
+            }
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.unwrap_or(0))
+            .build()
+            .unwrap_or_else(|_| {
+                command
+                    .error(ErrorKind::Io, "could not start worker thread pool")
+                    .exit();
+            });
+
+        let cache = Mutex::new(CacheIndex::load(&path));
+
+        let results: Vec<(PathBuf, Result<Outcome, String>)> = pool.install(|| {
+            files
+                .into_par_iter()
+                .map(|file_path| {
+                    let result = process_image(
+                        resize_mode,
+                        &file_path,
+                        keep_dimensions,
+                        force_crop,
+                        centre,
+                        overwrite,
+                        format,
+                        quality,
+                        sample,
+                        dither,
+                        Some(&cache),
+                    );
+
+                    (file_path, result)
+                })
+                .collect()
+        });
+
+        if cache.into_inner().unwrap().save().is_err() {
+            log("could not write the output cache index", LogType::Error);
+        }
+
+        let mut processed = 0;
+        let mut cached = 0;
+        let mut skipped = Vec::new();
+
+        for (file_path, result) in results {
+            match result {
+                Ok(Outcome::Processed) => processed += 1,
+                Ok(Outcome::Cached) => cached += 1,
+                Err(message) => skipped.push((file_path, message)),
             }
         }
+
+        for (file_path, message) in &skipped {
+            log(
+                &*format!("'{}': {}", file_path.display(), message),
+                LogType::Error,
+            );
+        }
+
+        println!(
+            "{} processed, {} already up to date, {} skipped",
+            processed,
+            cached,
+            skipped.len()
+        );
     }
 }
 
+enum Outcome {
+    Processed,
+    Cached,
+}
+
 fn process_image(
-    command: &mut Command,
-    scale_factor: u8,
+    resize_mode: ResizeMode,
+    path: &PathBuf,
+    keep_dimensions: bool,
+    force_crop: bool,
+    centre: bool,
+    overwrite: bool,
+    format_arg: FormatArg,
+    quality: u8,
+    sample: Sample,
+    dither: Dither,
+    cache: Option<&Mutex<CacheIndex>>,
+) -> Result<Outcome, String> {
+    let info = Info::probe(path)?;
+
+    let directory = path
+        .ancestors()
+        .nth(1)
+        .unwrap_or_else(|| Path::new("."))
+        .to_path_buf();
+
+    let original_file_name = path.file_name().unwrap().to_str().unwrap();
+
+    let format = Format::resolve(format_arg, quality, path);
+
+    let output_file_name = if info.is_animated_format() {
+        if overwrite {
+            original_file_name.to_string()
+        } else {
+            format!("{}{}", PIXELATED_PREFIX, original_file_name)
+        }
+    } else {
+        let stem = path.file_stem().unwrap().to_str().unwrap();
+        let new_name = format!("{}.{}", stem, format.extension());
+
+        if overwrite {
+            new_name
+        } else {
+            format!("{}{}", PIXELATED_PREFIX, new_name)
+        }
+    };
+
+    if let Some(cache) = cache {
+        let source_bytes = fs::read(path)
+            .map_err(|_| format!("could not open file at '{}'", path.display()))?;
+
+        let key = CacheKey::compute(
+            &source_bytes,
+            resize_mode,
+            keep_dimensions,
+            force_crop,
+            centre,
+            format,
+            sample,
+            dither,
+        );
+        let output_path = directory.join(&output_file_name);
+
+        if cache.lock().unwrap().is_fresh(&output_file_name, key, &output_path) {
+            return Ok(Outcome::Cached);
+        }
+
+        run_process(
+            &info,
+            resize_mode,
+            path,
+            keep_dimensions,
+            force_crop,
+            centre,
+            overwrite,
+            format_arg,
+            quality,
+            sample,
+            dither,
+        )?;
+
+        cache.lock().unwrap().record(output_file_name, key);
+
+        return Ok(Outcome::Processed);
+    }
+
+    run_process(
+        &info,
+        resize_mode,
+        path,
+        keep_dimensions,
+        force_crop,
+        centre,
+        overwrite,
+        format_arg,
+        quality,
+        sample,
+        dither,
+    )?;
+
+    Ok(Outcome::Processed)
+}
+
+fn run_process(
+    info: &Info,
+    resize_mode: ResizeMode,
     path: &PathBuf,
     keep_dimensions: bool,
     force_crop: bool,
     centre: bool,
     overwrite: bool,
-    error_response: ErrorResponse,
-) {
+    format_arg: FormatArg,
+    quality: u8,
+    sample: Sample,
+    dither: Dither,
+) -> Result<(), String> {
+    if info.is_animated_format() {
+        return animation::process_animated_image(
+            resize_mode,
+            path,
+            keep_dimensions,
+            force_crop,
+            centre,
+            overwrite,
+            sample,
+            dither,
+        );
+    }
+
+    process_still_image(
+        resize_mode,
+        path,
+        keep_dimensions,
+        force_crop,
+        centre,
+        overwrite,
+        format_arg,
+        quality,
+        sample,
+        dither,
+    )
+}
+
+fn process_still_image(
+    resize_mode: ResizeMode,
+    path: &PathBuf,
+    keep_dimensions: bool,
+    force_crop: bool,
+    centre: bool,
+    overwrite: bool,
+    format_arg: FormatArg,
+    quality: u8,
+    sample: Sample,
+    dither: Dither,
+) -> Result<(), String> {
     let mut image = match ImageReader::open(&path) {
         Ok(file) => match file.decode() {
             Ok(image) => image,
-            Err(_) => match error_response {
-                ErrorResponse::Exit => {
-                    command
-                        .error(ErrorKind::Io, "could not decode image")
-                        .exit();
-                }
-                ErrorResponse::Ignore => {
-                    log(
-                        &*format!("could not decode image at '{}'; skipping", path.display()),
-                        LogType::Error,
-                    );
-
-                    return;
-                }
-            },
-        },
-        Err(_) => match error_response {
-            ErrorResponse::Exit => {
-                command.error(ErrorKind::Io, "could not open file").exit();
-            }
-            ErrorResponse::Ignore => {
-                log(
-                    &*format!("could not open file at '{}'; skipping", path.display()),
-                    LogType::Error,
-                );
-
-                return;
+            Err(_) => {
+                return Err(format!("could not decode image at '{}'", path.display()));
             }
         },
+        Err(_) => {
+            return Err(format!("could not open file at '{}'", path.display()));
+        }
     };
 
     let (mut width, mut height) = (image.width(), image.height());
-
-    if width % (scale_factor as u32) != 0 || height % (scale_factor as u32) != 0 {
-        if !force_crop {
-            match error_response {
-                ErrorResponse::Exit => {
-                    command
-                        .error(ErrorKind::Io, "image dimensions must be divisible by scale factor. you can force crop the image using the -f flag")
-                        .exit();
-                }
-                ErrorResponse::Ignore => {
-                    log(
-                        &*format!("image dimensions at '{}' were not divisible by the scale factor. you can force crop the image using the -f flag", path.display()),
-                        LogType::Error,
-                    );
-
-                    return;
-                }
-            }
+    let scale_factor = resize_mode.block_size(width, height);
+
+    if width % scale_factor != 0 || height % scale_factor != 0 {
+        if !force_crop && !resize_mode.auto_crops() {
+            return Err(format!(
+                "image dimensions at '{}' were not divisible by the scale factor. you can force crop the image using the -f flag",
+                path.display()
+            ));
         } else {
             image = crop_image(&mut image, scale_factor, centre);
             (width, height) = (image.width(), image.height());
         }
     }
 
-    let (new_width, new_height) = (
-        width / (scale_factor as u32),
-        height / (scale_factor as u32),
-    );
+    let (new_width, new_height) = (width / scale_factor, height / scale_factor);
 
     let mut new_image = if keep_dimensions {
         image::DynamicImage::new_rgb8(width, height)
@@ -201,33 +475,23 @@ fn process_image(
 
     for x in 0..new_width {
         for y in 0..new_height {
-            if keep_dimensions {
-                let mut pixels: Vec<Rgba<u8>> =
-                    vec![Rgba([0, 0, 0, 0]); (scale_factor * scale_factor) as usize];
+            let mut pixels: Vec<Rgba<u8>> = Vec::with_capacity((scale_factor * scale_factor) as usize);
 
-                for i in 0..scale_factor {
-                    for j in 0..scale_factor {
-                        pixels[(i * scale_factor + j) as usize] = image.get_pixel(
-                            x * (scale_factor as u32) + i as u32,
-                            y * (scale_factor as u32) + j as u32,
-                        );
-                    }
+            for i in 0..scale_factor {
+                for j in 0..scale_factor {
+                    pixels.push(image.get_pixel(x * scale_factor + i, y * scale_factor + j));
                 }
+            }
 
-                let pixel = average_pixels(&pixels);
+            let pixel = dither.apply(sample.reduce(&pixels), x, y);
 
+            if keep_dimensions {
                 for i in 0..scale_factor {
                     for j in 0..scale_factor {
-                        new_image.put_pixel(
-                            x * (scale_factor as u32) + i as u32,
-                            y * (scale_factor as u32) + j as u32,
-                            pixel,
-                        );
+                        new_image.put_pixel(x * scale_factor + i, y * scale_factor + j, pixel);
                     }
                 }
             } else {
-                let pixel = image.get_pixel(x * (scale_factor as u32), y * (scale_factor as u32));
-
                 new_image.put_pixel(x, y, pixel);
             }
         }
@@ -239,38 +503,28 @@ fn process_image(
         .unwrap_or_else(|| Path::new("."))
         .display();
 
-    let original_file_name = &path.file_name().unwrap().to_str().unwrap();
+    let format = Format::resolve(format_arg, quality, path);
+
+    let original_stem = &path.file_stem().unwrap().to_str().unwrap();
+    let new_file_name = format!("{}.{}", original_stem, format.extension());
 
     let file_name = if overwrite {
-        format!("{}", original_file_name)
+        new_file_name
     } else {
-        format!("pixelated_{}", original_file_name)
+        format!("{}{}", PIXELATED_PREFIX, new_file_name)
     };
 
-    match new_image.save(format!("{}/{}", directory, file_name)) {
-        Ok(_) => return,
-        Err(_) => match error_response {
-            ErrorResponse::Exit => {
-                command.error(ErrorKind::Io, "could not save image").exit();
-            }
-            ErrorResponse::Ignore => {
-                log(
-                    &*format!("could not save image at '{}'", path.display()),
-                    LogType::Error,
-                );
-
-                return;
-            }
-        },
-    };
+    format
+        .save(&new_image, Path::new(&format!("{}/{}", directory, file_name)))
+        .map_err(|_| format!("could not save image at '{}'", path.display()))
 }
 
-fn crop_image(image: &mut DynamicImage, scale_factor: u8, centre: bool) -> DynamicImage {
+pub(crate) fn crop_image(image: &mut DynamicImage, scale_factor: u32, centre: bool) -> DynamicImage {
     let (width, height) = (image.width(), image.height());
 
     let (new_width, new_height) = (
-        width - (width % scale_factor as u32),
-        height - (height % scale_factor as u32),
+        width - (width % scale_factor),
+        height - (height % scale_factor),
     );
 
     let (x_offset, y_offset) = if centre {
@@ -282,29 +536,6 @@ fn crop_image(image: &mut DynamicImage, scale_factor: u8, centre: bool) -> Dynam
     image.crop(x_offset, y_offset, new_width, new_height)
 }
 
-fn average_pixels(pixels: &Vec<Rgba<u8>>) -> Rgba<u8> {
-    let mut red = 0;
-    let mut green = 0;
-    let mut blue = 0;
-    let mut alpha = 0;
-
-    for pixel in pixels {
-        red += pixel[0] as u32;
-        green += pixel[1] as u32;
-        blue += pixel[2] as u32;
-        alpha += pixel[3] as u32;
-    }
-
-    let pixel_count = pixels.len() as u32;
-
-    Rgba([
-        (red / pixel_count) as u8,
-        (green / pixel_count) as u8,
-        (blue / pixel_count) as u8,
-        (alpha / pixel_count) as u8,
-    ])
-}
-
 enum LogType {
     // Info,
     Error,
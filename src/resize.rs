@@ -0,0 +1,71 @@
+/// How the pixelation block size for an image is chosen.
+#[derive(Clone, Copy)]
+pub enum ResizeMode {
+    /// Pixelate using a fixed, user-specified block size.
+    ScaleDown(u32),
+    /// Choose a block size so the output is about `width` pixels wide.
+    FitWidth(u32),
+    /// Choose a block size so the output is about `height` pixels tall.
+    FitHeight(u32),
+    /// Choose a block size so the output fits within `width`x`height`, preserving aspect ratio.
+    Fit(u32, u32),
+}
+
+impl ResizeMode {
+    /// Resolve this mode into a concrete pixelation block size for an image of the given
+    /// source dimensions.
+    pub fn block_size(&self, width: u32, height: u32) -> u32 {
+        match *self {
+            ResizeMode::ScaleDown(factor) => factor,
+            ResizeMode::FitWidth(target_width) => Self::ratio(width, target_width),
+            ResizeMode::FitHeight(target_height) => Self::ratio(height, target_height),
+            ResizeMode::Fit(target_width, target_height) => {
+                // Round up rather than to nearest, so neither side of the output exceeds its target.
+                let ceil_ratio = |source: u32, target: u32| -> u32 {
+                    let target = target.max(1) as u64;
+                    ((source as u64 + target - 1) / target).max(1) as u32
+                };
+
+                ceil_ratio(width, target_width).max(ceil_ratio(height, target_height))
+            }
+        }
+    }
+
+    fn ratio(source: u32, target: u32) -> u32 {
+        ((source as f64 / target.max(1) as f64).round() as u32).max(1)
+    }
+
+    /// Whether this mode derives its block size from a target size rather than taking it
+    /// directly from the user. The derived size almost never divides the source evenly, so
+    /// these modes crop automatically instead of requiring `-f` for the common case.
+    pub fn auto_crops(&self) -> bool {
+        !matches!(self, ResizeMode::ScaleDown(_))
+    }
+
+    /// A tag plus the mode's defining numbers, stable enough to use as part of a cache key
+    /// without having to decode the image first.
+    pub fn fingerprint(&self) -> (u8, u32, u32) {
+        match *self {
+            ResizeMode::ScaleDown(factor) => (0, factor, 0),
+            ResizeMode::FitWidth(width) => (1, width, 0),
+            ResizeMode::FitHeight(height) => (2, 0, height),
+            ResizeMode::Fit(width, height) => (3, width, height),
+        }
+    }
+}
+
+/// Parse a `WIDTHxHEIGHT` value for `--fit`, e.g. `128x128`.
+pub fn parse_fit_dimensions(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| "expected WIDTHxHEIGHT, e.g. 128x128".to_string())?;
+
+    let width = width
+        .parse::<u32>()
+        .map_err(|_| "invalid width in --fit".to_string())?;
+    let height = height
+        .parse::<u32>()
+        .map_err(|_| "invalid height in --fit".to_string())?;
+
+    Ok((width, height))
+}
@@ -0,0 +1,91 @@
+use image::Rgba;
+use std::collections::HashMap;
+
+/// The strategy used to reduce a pixelation block down to a single representative pixel.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Sample {
+    /// The flat average of every pixel in the block.
+    Mean,
+    /// The per-channel median, which resists outlier pixels better than the mean.
+    Median,
+    /// The most frequent exact color in the block, good for pixel art and logos.
+    Mode,
+    /// The block's top-left pixel, taken as-is.
+    Nearest,
+}
+
+impl Sample {
+    /// A stable tag for this strategy, used as part of a cache key.
+    pub fn tag(&self) -> u8 {
+        match self {
+            Sample::Mean => 0,
+            Sample::Median => 1,
+            Sample::Mode => 2,
+            Sample::Nearest => 3,
+        }
+    }
+
+    pub fn reduce(&self, pixels: &[Rgba<u8>]) -> Rgba<u8> {
+        match self {
+            Sample::Mean => Self::mean(pixels),
+            Sample::Median => Self::median(pixels),
+            Sample::Mode => Self::mode(pixels),
+            Sample::Nearest => pixels[0],
+        }
+    }
+
+    fn mean(pixels: &[Rgba<u8>]) -> Rgba<u8> {
+        let mut red = 0u32;
+        let mut green = 0u32;
+        let mut blue = 0u32;
+        let mut alpha = 0u32;
+
+        for pixel in pixels {
+            red += pixel[0] as u32;
+            green += pixel[1] as u32;
+            blue += pixel[2] as u32;
+            alpha += pixel[3] as u32;
+        }
+
+        let pixel_count = pixels.len() as u32;
+
+        Rgba([
+            (red / pixel_count) as u8,
+            (green / pixel_count) as u8,
+            (blue / pixel_count) as u8,
+            (alpha / pixel_count) as u8,
+        ])
+    }
+
+    fn median(pixels: &[Rgba<u8>]) -> Rgba<u8> {
+        let channel_median = |channel: usize| -> u8 {
+            let mut values: Vec<u8> = pixels.iter().map(|pixel| pixel[channel]).collect();
+            values.sort_unstable();
+
+            values[values.len() / 2]
+        };
+
+        Rgba([
+            channel_median(0),
+            channel_median(1),
+            channel_median(2),
+            channel_median(3),
+        ])
+    }
+
+    fn mode(pixels: &[Rgba<u8>]) -> Rgba<u8> {
+        let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+
+        for pixel in pixels {
+            *counts.entry(pixel.0).or_insert(0) += 1;
+        }
+
+        let most_common = counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(pixel, _)| pixel)
+            .unwrap_or(pixels[0].0);
+
+        Rgba(most_common)
+    }
+}